@@ -0,0 +1,156 @@
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use super::Args;
+use super::TimestampFormat;
+
+/// Upper bound on delimiter-framed leftover bytes kept across reads
+///
+/// Bounds memory when the configured delimiter never appears (wrong
+/// `--delimiter` for the data, or a binary feed), mirroring the overrun drop in
+/// the per-client queue. `FixedLen` is already self-bounding by its length.
+const MAX_FRAME_BYTES: usize = 1024 * 1024;
+
+/// How raw serial bytes are grouped into frames before dispatch
+enum Framing {
+    /// Pass each read through unchanged (default)
+    Raw,
+    /// Emit one frame per occurrence of a delimiter byte (delimiter included)
+    Delimiter(u8),
+    /// Emit fixed-length frames of the given number of bytes
+    FixedLen(usize),
+}
+
+/// Stateful accumulator turning serial reads into complete records
+///
+/// Leftover bytes are kept across reads, so a frame split over two `read`
+/// calls is still emitted whole — the same incremental approach as the e-bike
+/// tracker's `Parser::consume`. Each emitted frame becomes one `Avb` unit sent
+/// to clients, guaranteeing every TCP write is a complete record. When no
+/// framing option is given the behavior is raw pass-through.
+pub(crate) struct Framer {
+    framing: Framing,
+    buf: Vec<u8>,
+    timestamp: Option<TimestampFormat>,
+    start: Instant,
+}
+
+impl Framer {
+    pub(crate) fn new(args: &Args) -> Self {
+        let framing = if let Some(delim) = args.delimiter {
+            Framing::Delimiter(delim)
+        } else if let Some(len) = args.frame_len.filter(|&n| n > 0) {
+            Framing::FixedLen(len)
+        } else {
+            Framing::Raw
+        };
+        let timestamp = args.timestamp.then_some(args.timestamp_format);
+        Self {
+            framing,
+            buf: Vec::new(),
+            timestamp,
+            start: Instant::now(),
+        }
+    }
+
+    /// Feed freshly read bytes and return any complete frames they produced
+    pub(crate) fn consume(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        match self.framing {
+            Framing::Raw => {
+                if !data.is_empty() {
+                    frames.push(data.to_vec());
+                }
+            }
+            Framing::Delimiter(delim) => {
+                self.buf.extend_from_slice(data);
+                while let Some(pos) = self.buf.iter().position(|&b| b == delim) {
+                    frames.push(self.buf.drain(..=pos).collect());
+                }
+                // Drop the oldest unframed bytes if no delimiter ever arrives,
+                // keeping the newest data and bounding memory.
+                if self.buf.len() > MAX_FRAME_BYTES {
+                    let overflow = self.buf.len() - MAX_FRAME_BYTES;
+                    self.buf.drain(..overflow);
+                    eprintln!(
+                        "Frame exceeded {MAX_FRAME_BYTES} bytes without delimiter; dropped {overflow} oldest bytes"
+                    );
+                }
+            }
+            Framing::FixedLen(len) => {
+                self.buf.extend_from_slice(data);
+                while self.buf.len() >= len {
+                    frames.push(self.buf.drain(..len).collect());
+                }
+            }
+        }
+        if self.timestamp.is_some() {
+            for frame in &mut frames {
+                let mut stamped = self.timestamp_prefix();
+                stamped.append(frame);
+                *frame = stamped;
+            }
+        }
+        frames
+    }
+
+    /// Render the configured timestamp as an ASCII prefix for a frame
+    fn timestamp_prefix(&self) -> Vec<u8> {
+        let stamp = match self.timestamp {
+            Some(TimestampFormat::Monotonic) => {
+                let elapsed = self.start.elapsed();
+                format!("[{}.{:06}] ", elapsed.as_secs(), elapsed.subsec_micros())
+            }
+            Some(TimestampFormat::WallClock) => match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(since) => format!("[{}.{:03}] ", since.as_secs(), since.subsec_millis()),
+                Err(_) => "[0.000] ".to_string(),
+            },
+            None => String::new(),
+        };
+        stamp.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn framer(extra: &[&str]) -> Framer {
+        let mut argv = vec!["ser2tcp", "/dev/null"];
+        argv.extend_from_slice(extra);
+        Framer::new(&Args::parse_from(argv))
+    }
+
+    #[test]
+    fn raw_passes_each_read_through() {
+        let mut framer = framer(&[]);
+        assert_eq!(framer.consume(b"abc"), vec![b"abc".to_vec()]);
+        assert!(framer.consume(b"").is_empty());
+    }
+
+    #[test]
+    fn delimiter_reassembles_frame_split_across_reads() {
+        let mut framer = framer(&["--delimiter", "0x0A"]);
+        assert!(framer.consume(b"hel").is_empty());
+        assert_eq!(framer.consume(b"lo\nwor"), vec![b"hello\n".to_vec()]);
+        assert_eq!(framer.consume(b"ld\n"), vec![b"world\n".to_vec()]);
+    }
+
+    #[test]
+    fn fixed_len_emits_whole_records_and_keeps_leftover() {
+        let mut framer = framer(&["--frame-len", "4"]);
+        assert!(framer.consume(b"ab").is_empty());
+        assert_eq!(framer.consume(b"cdef"), vec![b"abcd".to_vec()]);
+        assert_eq!(framer.consume(b"gh"), vec![b"efgh".to_vec()]);
+    }
+
+    #[test]
+    fn delimiter_without_match_is_bounded() {
+        let mut framer = framer(&["--delimiter", "0x0A"]);
+        let big = vec![b'x'; MAX_FRAME_BYTES + 100];
+        assert!(framer.consume(&big).is_empty());
+        assert!(framer.buf.len() <= MAX_FRAME_BYTES);
+    }
+}
@@ -5,16 +5,49 @@ use std::{
 
 pub use self::error::{Error, Result};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use wild::ArgsOs;
 
 mod error;
+mod frame;
+mod mqtt;
 mod serial;
 mod tcp;
 
 type Avb = Arc<Vec<u8>>;
 
-#[derive(Parser, Debug)]
+/// Desired state of a modem control output line after opening the port
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum LineState {
+    /// Drive the line active (logic high)
+    Assert,
+    /// Drive the line inactive (logic low)
+    Deassert,
+    /// Leave the line in whatever state the driver opened it
+    Keep,
+}
+
+/// Clock used for the optional per-frame timestamp prefix
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum TimestampFormat {
+    /// Seconds since program start (monotonic)
+    Monotonic,
+    /// Seconds since the Unix epoch (wall-clock)
+    WallClock,
+}
+
+/// Parse a byte value given in decimal (`10`) or hex (`0x0A`) notation
+fn parse_byte(s: &str) -> core::result::Result<u8, String> {
+    let s = s.trim();
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u8::from_str_radix(hex, 16)
+    } else {
+        s.parse::<u8>()
+    };
+    parsed.map_err(|e| format!("invalid byte value '{s}': {e}"))
+}
+
+#[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 struct Args {
     /// Serial port to connect to
@@ -49,37 +82,150 @@ struct Args {
     /// Valid values: [N]one, [H]ardware, [S]oftware
     #[arg(short('f'), long, default_value_t = 'N')]
     flow_control: char,
+    /// Bidirectional bridge: forward TCP client input back to the serial port
+    ///
+    /// Bytes received from any connected TCP client are written to the serial
+    /// port. Multiple clients writing concurrently interleave at buffer
+    /// granularity. Off by default, so the bridge stays read-only (serial →
+    /// TCP) unless requested.
+    #[arg(short('B'), long)]
+    bidirectional: bool,
+    /// Data Terminal Ready (DTR) output line state after opening
+    ///
+    /// Driving DTR/RTS a particular way is needed by many devices to boot or
+    /// to avoid auto-reset (e.g. ESP32 wired to DTR/RTS).
+    #[arg(long, value_enum, default_value_t = LineState::Keep)]
+    dtr: LineState,
+    /// Request To Send (RTS) output line state after opening
+    #[arg(long, value_enum, default_value_t = LineState::Keep)]
+    rts: LineState,
+    /// Monitor modem status lines (CTS/DSR/DCD/RI) and log edge transitions
+    #[arg(long)]
+    monitor_modem: bool,
+    /// Modem status poll interval in milliseconds
+    #[arg(long, default_value_t = 100)]
+    monitor_interval: u64,
+    /// Maximum bytes buffered per TCP client before the oldest data is dropped
+    ///
+    /// Bounds per-client memory when a socket stalls; on overrun the oldest
+    /// chunks are discarded to keep the newest data and a per-peer dropped-byte
+    /// counter is logged.
+    #[arg(long, default_value_t = 1_048_576)]
+    client_buffer_bytes: usize,
+    /// Emit one frame per delimiter byte (e.g. 0x0A for line-based output)
+    #[arg(long, value_parser = parse_byte)]
+    delimiter: Option<u8>,
+    /// Emit fixed-length frames of N bytes instead of raw reads
+    #[arg(long, conflicts_with = "delimiter")]
+    frame_len: Option<usize>,
+    /// Prefix each frame with a timestamp before dispatch
+    #[arg(long)]
+    timestamp: bool,
+    /// Timestamp clock used when --timestamp is set
+    #[arg(long, value_enum, default_value_t = TimestampFormat::WallClock)]
+    timestamp_format: TimestampFormat,
+    /// Also publish serial data to an MQTT broker (host:port)
+    #[arg(long)]
+    mqtt_broker: Option<String>,
+    /// MQTT topic to publish serial data to
+    #[arg(long, default_value = "ser2tcp")]
+    mqtt_topic: String,
+    /// MQTT QoS level (only 0, at-most-once, is currently supported)
+    #[arg(long, default_value_t = 0)]
+    mqtt_qos: u8,
+    /// Match the serial port by USB identity instead of a fixed device node
+    ///
+    /// Format: VID:PID[:serial] (hex VID/PID). Lets reconnection find the
+    /// device again even if the OS reassigns its node (e.g. ttyUSB0 → ttyUSB1).
+    #[arg(long)]
+    match_usb: Option<String>,
 }
 
 pub fn run(args: ArgsOs) -> Result<()> {
     let args = Args::parse_from(args);
     //println!("{args:?}");
-    if args.port.is_none() || args.list_available_ports {
+    if args.list_available_ports || (args.port.is_none() && args.match_usb.is_none()) {
         return serial::list_available_ports();
     }
-    if let Some(port) = &args.port {
-        let sport = serial::open_serial_port(port, &args)?;
+    // The MQTT sink publishes at QoS 0 only; higher levels would need the
+    // PUBACK/PUBREC handshake the sink does not implement, so reject them.
+    if args.mqtt_qos != 0 {
+        return Err(std::io::Error::other(format!(
+            "MQTT QoS {} is not supported (only 0, at-most-once)",
+            args.mqtt_qos
+        ))
+        .into());
+    }
+    {
+        let sport = serial::open_configured_port(&args)?;
         eprintln!("Using serial port: {:#?}", sport);
-        // Create thread for serial port reader
+        // Share one reconnectable handle across the reader, writer and monitor
+        // so a reconnect in the reader is seen by every thread.
+        let sport = Arc::new(Mutex::new(sport));
+        // Create thread for serial port writer (TCP → serial) when bridging
+        let serial_write_tx = if args.bidirectional {
+            let writer_port = Arc::clone(&sport);
+            let (serial_write_tx, serial_write_rx) = mpsc::channel();
+            thread::spawn(move || {
+                serial::handle_serial_writer(writer_port, serial_write_rx);
+            });
+            Some(serial_write_tx)
+        } else {
+            None
+        };
+        // Create thread for modem status monitoring
+        if args.monitor_modem {
+            let monitor_port = Arc::clone(&sport);
+            let interval = std::time::Duration::from_millis(args.monitor_interval);
+            thread::spawn(move || {
+                serial::monitor_modem_status(monitor_port, interval);
+            });
+        }
+        // Create thread for optional MQTT publish sink
+        let mqtt_sink_tx = if let Some(broker) = args.mqtt_broker.clone() {
+            let topic = args.mqtt_topic.clone();
+            let (mqtt_tx, mqtt_rx) = mpsc::channel();
+            thread::spawn(move || {
+                mqtt::handle_mqtt_sink(broker, topic, mqtt_rx);
+            });
+            Some(mqtt_tx)
+        } else {
+            None
+        };
+        // Create thread for serial port reader, with its own Args copy so it
+        // can re-open the port on hotplug/disconnect while the rest of run()
+        // keeps serving already-connected TCP clients.
+        let framer = frame::Framer::new(&args);
+        let reader_args = args.clone();
         let (serial_reader_tx, serial_reader_rx) = mpsc::channel();
-        let serial_reader = thread::spawn(|| {
-            serial::handle_serial_port(sport, serial_reader_tx);
+        let serial_reader = thread::spawn(move || {
+            serial::handle_serial_port(sport, serial_reader_tx, framer, reader_args);
         });
         let tcp_write_senders = Arc::new(Mutex::new(Vec::new()));
         // Create thread for TCP listener
         let tcp_write_senders_for_listener = Arc::clone(&tcp_write_senders);
+        let client_buffer_bytes = args.client_buffer_bytes;
         let listener_thread = thread::spawn(move || {
-            tcp::handle_tcp_listener(&args.listen, tcp_write_senders_for_listener);
+            tcp::handle_tcp_listener(
+                &args.listen,
+                tcp_write_senders_for_listener,
+                serial_write_tx,
+                client_buffer_bytes,
+            );
         });
         // Read data for serial port and dispatch to each TCP stream writer
         for buf in serial_reader_rx {
             let buf = Arc::new(buf);
             //print!("{}", String::from_utf8_lossy(buf.as_slice()));
+            // Fan out to the MQTT sink in parallel with the TCP clients
+            if let Some(mqtt_sink_tx) = &mqtt_sink_tx {
+                let _ = mqtt_sink_tx.send(buf.clone());
+            }
             let Ok(mut tcp_write_senders) = tcp_write_senders.lock() else {
                 continue;
             };
-            // Send data and remove sender if error occurs
-            tcp_write_senders.retain_mut(|tx| tx.send(buf.clone()).is_ok());
+            // Push data and remove the queue if the client has closed
+            tcp_write_senders.retain(|queue| queue.push(buf.clone()));
         }
         serial_reader.join()?;
         listener_thread.join()?;
@@ -0,0 +1,102 @@
+use std::net::TcpStream;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::Duration;
+
+use mqtt::Decodable;
+use mqtt::Encodable;
+use mqtt::TopicName;
+use mqtt::control::variable_header::ConnectReturnCode;
+use mqtt::packet::ConnectPacket;
+use mqtt::packet::PublishPacket;
+use mqtt::packet::QoSWithPacketIdentifier;
+use mqtt::packet::VariablePacket;
+
+use super::Avb;
+
+const BACKOFF_INITIAL: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Publish framed/raw serial buffers to an MQTT broker
+///
+/// Registered alongside the TCP fan-out as an additional sink: it drains its
+/// own `Receiver<Avb>`, which the dispatcher feeds with the same buffers it
+/// queues for the TCP clients, and publishes each buffer to `topic`. On a
+/// broker disconnect it reconnects with exponential backoff (capped), the same
+/// drop-and-recover spirit as `tcp_write_senders.retain` dropping dead peers.
+///
+/// Only QoS 0 (at most once) is published: the sink does not track in-flight
+/// packet ids or read PUBACK/PUBREC acknowledgements, so higher levels are not
+/// honored.
+pub(crate) fn handle_mqtt_sink(broker: String, topic: String, rx: Receiver<Avb>) {
+    let topic_name = match TopicName::new(topic.clone()) {
+        Ok(topic_name) => topic_name,
+        Err(e) => {
+            eprintln!("Invalid MQTT topic '{topic}': {e}");
+            return;
+        }
+    };
+    let mut backoff = BACKOFF_INITIAL;
+    'reconnect: loop {
+        let mut stream = match connect(&broker) {
+            Ok(stream) => {
+                eprintln!("Connected to MQTT broker: {broker}");
+                backoff = BACKOFF_INITIAL;
+                stream
+            }
+            Err(e) => {
+                eprintln!("MQTT connect to {broker} failed: {e}; retrying in {backoff:?}");
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(BACKOFF_MAX);
+                continue;
+            }
+        };
+        loop {
+            // Block until the next serial buffer arrives; a closed channel
+            // means the producer is gone and the sink can stop entirely.
+            let Ok(buf) = rx.recv() else {
+                return;
+            };
+            let packet = PublishPacket::new(
+                topic_name.clone(),
+                QoSWithPacketIdentifier::Level0,
+                buf.as_slice().to_vec(),
+            );
+            if let Err(e) = packet.encode(&mut stream) {
+                eprintln!("MQTT publish failed: {e}; reconnecting");
+                continue 'reconnect;
+            }
+        }
+    }
+}
+
+/// Open a TCP connection to the broker, send CONNECT and verify the CONNACK
+fn connect(broker: &str) -> std::io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(broker)?;
+    let mut connect = ConnectPacket::new("ser2tcp");
+    connect.set_clean_session(true);
+    connect.encode(&mut stream).map_err(to_io)?;
+    // The broker may refuse the session (not authorized, bad credentials, ...);
+    // check the CONNACK return code before treating the link as up.
+    match VariablePacket::decode(&mut stream).map_err(to_io)? {
+        VariablePacket::ConnackPacket(ack) => {
+            if ack.connect_return_code() != ConnectReturnCode::ConnectionAccepted {
+                return Err(std::io::Error::other(format!(
+                    "MQTT broker refused connection: {:?}",
+                    ack.connect_return_code()
+                )));
+            }
+        }
+        other => {
+            return Err(std::io::Error::other(format!(
+                "expected CONNACK from MQTT broker, got {other:?}"
+            )));
+        }
+    }
+    Ok(stream)
+}
+
+/// Wrap an MQTT packet encoding or decoding error as an `io::Error` for a uniform signature
+fn to_io<E: std::fmt::Display>(e: E) -> std::io::Error {
+    std::io::Error::other(e.to_string())
+}
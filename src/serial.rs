@@ -1,8 +1,23 @@
 use serialport::SerialPort;
-use std::sync::mpsc::Sender;
+use std::io::Write; // for write_all()
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 use super::Args;
+use super::Avb;
+use super::LineState;
 use super::error::Result;
+use super::frame::Framer;
+
+/// A serial port handle shared between the reader, writer and monitor threads
+///
+/// Held behind a mutex so the reader can swap in a fresh handle on reconnect
+/// and every thread transparently starts using the re-opened device.
+pub(crate) type SharedPort = Arc<Mutex<Box<dyn SerialPort>>>;
 
 /// Print a list of available serial ports to console
 pub(crate) fn list_available_ports() -> Result<()> {
@@ -42,6 +57,88 @@ pub(crate) fn list_available_ports() -> Result<()> {
     Ok(())
 }
 
+/// Initial reconnect delay after the serial port drops
+const RECONNECT_INITIAL: Duration = Duration::from_millis(500);
+/// Maximum reconnect delay (exponential backoff is capped here)
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+/// Read timeout so the reader periodically releases the shared port lock
+///
+/// The reader holds the shared handle only for the duration of one `read`; a
+/// finite timeout means a quiet device still yields the lock within this window
+/// so the writer (TCP → serial) and the modem monitor can take it.
+const SERIAL_READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Open the configured serial port, resolving `--match-usb` if given
+///
+/// With `--match-usb VID:PID[:serial]` the port node is looked up by USB
+/// identity so a reassigned node (e.g. ttyUSB0 → ttyUSB1) is still found;
+/// otherwise the positional port argument is used.
+pub(crate) fn open_configured_port(args: &Args) -> Result<Box<dyn SerialPort>> {
+    let port = resolve_port(args)?;
+    open_serial_port(&port, args)
+}
+
+/// Resolve the device node to open from `--match-usb` or the positional port
+fn resolve_port(args: &Args) -> Result<String> {
+    if let Some(spec) = &args.match_usb {
+        return match find_usb_port(spec)? {
+            Some(port) => Ok(port),
+            None => Err(serialport::Error::new(
+                serialport::ErrorKind::NoDevice,
+                format!("no USB serial port matching '{spec}'"),
+            )
+            .into()),
+        };
+    }
+    match &args.port {
+        Some(port) => Ok(port.clone()),
+        None => Err(serialport::Error::new(
+            serialport::ErrorKind::NoDevice,
+            "no serial port specified",
+        )
+        .into()),
+    }
+}
+
+/// Find the device node of a USB serial port matching `VID:PID[:serial]`
+fn find_usb_port(spec: &str) -> Result<Option<String>> {
+    let mut parts = spec.split(':');
+    let (Some(vid), Some(pid)) = (parts.next(), parts.next()) else {
+        return Err(serialport::Error::new(
+            serialport::ErrorKind::InvalidInput,
+            format!("invalid --match-usb '{spec}', expected VID:PID[:serial]"),
+        )
+        .into());
+    };
+    let vid = parse_hex_u16(vid, spec)?;
+    let pid = parse_hex_u16(pid, spec)?;
+    let serial = parts.next();
+    for port in serialport::available_ports()? {
+        if let serialport::SerialPortType::UsbPort(info) = &port.port_type {
+            if info.vid == vid && info.pid == pid {
+                if let Some(want) = serial {
+                    if info.serial_number.as_deref() != Some(want) {
+                        continue;
+                    }
+                }
+                return Ok(Some(port.port_name.clone()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Parse a hex VID/PID field, reporting the offending `--match-usb` spec
+fn parse_hex_u16(value: &str, spec: &str) -> Result<u16> {
+    u16::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| {
+        serialport::Error::new(
+            serialport::ErrorKind::InvalidInput,
+            format!("invalid hex value '{value}' in --match-usb '{spec}'"),
+        )
+        .into()
+    })
+}
+
 /// Configure and open serial port using CLI arguments
 ///
 /// ESP32 uses 115200 8N1
@@ -84,29 +181,164 @@ pub(crate) fn open_serial_port(port: &str, args: &Args) -> Result<Box<dyn Serial
             serialport::FlowControl::None
         }
     });
-    Ok(builder.open()?)
+    // Bounded read timeout so the reader yields the shared handle between reads
+    builder = builder.timeout(SERIAL_READ_TIMEOUT);
+    let mut port = builder.open()?;
+    // Drive the modem control output lines if requested
+    match args.dtr {
+        LineState::Assert => port.write_data_terminal_ready(true)?,
+        LineState::Deassert => port.write_data_terminal_ready(false)?,
+        LineState::Keep => {}
+    }
+    match args.rts {
+        LineState::Assert => port.write_request_to_send(true)?,
+        LineState::Deassert => port.write_request_to_send(false)?,
+        LineState::Keep => {}
+    }
+    Ok(port)
 }
 
-/// Read data from serial port and send it to the serial data receiver channel
-pub(crate) fn handle_serial_port(mut port: Box<dyn SerialPort>, tx: Sender<Vec<u8>>) {
+/// Poll the modem status lines and log edge transitions to stderr
+///
+/// Watches CTS, DSR, DCD and RI so handshake lines can be observed changing
+/// while debugging. The initial reading is logged in full; subsequent polls
+/// only report lines that changed state.
+pub(crate) fn monitor_modem_status(port: SharedPort, interval: Duration) {
+    let names = ["CTS", "DSR", "DCD", "RI"];
+    let mut last: Option<[bool; 4]> = None;
+    let mut last_error_log: Option<Instant> = None;
+    loop {
+        let readings = {
+            let Ok(mut port) = port.lock() else {
+                return;
+            };
+            [
+                port.read_clear_to_send(),
+                port.read_data_set_ready(),
+                port.read_carrier_detect(),
+                port.read_ring_indicator(),
+            ]
+        };
+        let mut state = [false; 4];
+        let mut ok = true;
+        for (i, reading) in readings.iter().enumerate() {
+            match reading {
+                Ok(v) => state[i] = *v,
+                Err(e) => {
+                    ok = false;
+                    // The handle goes bad every poll while the reader is in its
+                    // reconnect backoff; rate-limit so it does not spam stderr.
+                    let now = Instant::now();
+                    let should_log = match last_error_log {
+                        Some(t) => now.duration_since(t) >= Duration::from_secs(1),
+                        None => true,
+                    };
+                    if should_log {
+                        eprintln!("Reading modem status line {} failed: {}", names[i], e);
+                        last_error_log = Some(now);
+                    }
+                }
+            }
+        }
+        if ok {
+            match last {
+                None => eprintln!(
+                    "Modem status: CTS={} DSR={} DCD={} RI={}",
+                    state[0], state[1], state[2], state[3]
+                ),
+                Some(prev) => {
+                    for i in 0..state.len() {
+                        if state[i] != prev[i] {
+                            let edge = if state[i] { "asserted" } else { "deasserted" };
+                            eprintln!("Modem status {} {edge}", names[i]);
+                        }
+                    }
+                }
+            }
+            last = Some(state);
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// Read data from serial port and send framed records to the receiver channel
+///
+/// The `framer` turns raw reads into complete records (raw pass-through by
+/// default); each emitted frame is sent as its own unit.
+pub(crate) fn handle_serial_port(
+    port: SharedPort,
+    tx: Sender<Vec<u8>>,
+    mut framer: Framer,
+    args: Args,
+) {
+    let mut backoff = RECONNECT_INITIAL;
     loop {
         let mut buf = [0; 1024];
-        match port.read(&mut buf) {
+        // Hold the shared handle only for the read itself so the writer and
+        // monitor threads can take the lock between reads.
+        let result = {
+            let Ok(mut port) = port.lock() else {
+                return;
+            };
+            port.read(&mut buf)
+        };
+        match result {
             Ok(n) => {
-                let v = buf[..n].to_vec();
-                match tx.send(v) {
-                    Ok(_) => continue,
-                    Err(e) => {
+                for frame in framer.consume(&buf[..n]) {
+                    if let Err(e) = tx.send(frame) {
                         eprintln!("Error sending data from serial port reader: {}", e);
-                        break;
+                        return;
                     }
                 }
             }
             Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
             Err(e) => {
-                eprintln!("Reading from serial port failed: {}", e);
-                break;
+                // Re-open the port and swap the fresh handle into the shared
+                // slot so a USB adapter that was unplugged recovers without a
+                // restart, and the writer/monitor pick up the new device too.
+                eprintln!("Reading from serial port failed: {e}; reconnecting");
+                let new_port = reconnect_serial_port(&args, &mut backoff);
+                let Ok(mut port) = port.lock() else {
+                    return;
+                };
+                *port = new_port;
+            }
+        }
+    }
+}
+
+/// Re-open the serial port with capped exponential backoff until it succeeds
+fn reconnect_serial_port(args: &Args, backoff: &mut Duration) -> Box<dyn SerialPort> {
+    loop {
+        thread::sleep(*backoff);
+        match open_configured_port(args) {
+            Ok(port) => {
+                eprintln!("Reconnected to serial port");
+                *backoff = RECONNECT_INITIAL;
+                return port;
+            }
+            Err(e) => {
+                *backoff = (*backoff * 2).min(RECONNECT_MAX);
+                eprintln!("Re-opening serial port failed: {e}; retrying in {backoff:?}");
             }
         }
     }
 }
+
+/// Drain the serial-write channel and write each buffer to the serial port
+///
+/// Fed by the TCP stream readers when running in bidirectional mode. Buffers
+/// from different clients arrive on the shared channel and are written in
+/// receive order, so concurrent writers interleave at buffer granularity.
+pub(crate) fn handle_serial_writer(port: SharedPort, rx: Receiver<Avb>) {
+    for buf in rx {
+        let Ok(mut port) = port.lock() else {
+            return;
+        };
+        // A failed write just drops this buffer; the reader reconnects the
+        // shared handle, so the next buffer is written to the fresh device.
+        if let Err(e) = port.write_all(buf.as_slice()) {
+            eprintln!("Writing to serial port failed: {}", e);
+        }
+    }
+}
@@ -1,71 +1,278 @@
-use std::io::Write; // for write_all()
-use std::net::TcpListener;
-use std::net::TcpStream;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::sync::mpsc;
-use std::sync::mpsc::Receiver;
-use std::sync::mpsc::Sender;
-use std::thread;
-
-use super::Avb;
-
-/// Create TCP listener on specified bind address+port
-///
-/// Create a channel for each connected TCP client and add it to the list of serial data receivers
-pub(crate) fn handle_tcp_listener(
-    bind_addr: &str,
-    tcp_write_senders: Arc<Mutex<Vec<Sender<Avb>>>>,
-) {
-    let tcp_listener = TcpListener::bind(bind_addr);
-    let Ok(tcp_listener) = tcp_listener else {
-        return;
-    };
-    let Ok(local_addr) = tcp_listener.local_addr() else {
-        return;
-    };
-    eprintln!("Listening on: {local_addr}");
-    let mut tcp_stream_threads = Vec::new();
-    for stream in tcp_listener.incoming() {
-        let Ok(stream) = stream else {
-            continue;
-        };
-        let (tcp_write_tx, tcp_write_rx) = mpsc::channel();
-        {
-            let Ok(mut tcp_write_senders) = tcp_write_senders.lock() else {
-                continue;
-            };
-            tcp_write_senders.push(tcp_write_tx);
-        }
-        let thread = thread::spawn(move || {
-            handle_tcp_stream(stream, tcp_write_rx);
-        });
-        tcp_stream_threads.push(thread);
-    }
-    for thread in tcp_stream_threads {
-        match thread.join() {
-            Ok(_) => continue,
-            Err(e) => {
-                eprintln!("Unable to join TCP stream thread: {e:?}");
-                continue;
-            }
-        }
-    }
-}
-
-/// Write received data from the serial reader channel to the TCP stream
-pub(crate) fn handle_tcp_stream(mut stream: TcpStream, tcp_writer_rx: Receiver<Avb>) {
-    let Ok(peer_addr) = stream.peer_addr() else {
-        return;
-    };
-    eprintln!("New connection from: {peer_addr}");
-    for buf in tcp_writer_rx {
-        match stream.write_all(buf.as_slice()) {
-            Ok(_) => continue,
-            Err(e) => {
-                eprintln!("Closed connection from: {peer_addr}: {e}");
-                break;
-            }
-        }
-    }
-}
+use std::collections::VecDeque;
+use std::io::Read; // for read()
+use std::io::Write; // for write_all()
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::Avb;
+
+/// Bounded per-client FIFO of pending serial buffers
+///
+/// Replaces the previous unbounded `mpsc::channel` so a slow or stalled socket
+/// cannot make its queue grow without limit. When a push would exceed
+/// `max_bytes` the oldest buffered chunks are dropped to make room for the
+/// newest data (the LSR overrun concept), the dropped bytes are accounted per
+/// peer, and a rate-limited warning is logged.
+pub(crate) struct ClientQueue {
+    inner: Mutex<ClientQueueInner>,
+    condvar: Condvar,
+    max_bytes: usize,
+    peer_addr: SocketAddr,
+}
+
+struct ClientQueueInner {
+    queue: VecDeque<Avb>,
+    queued_bytes: usize,
+    dropped_bytes: u64,
+    closed: bool,
+    last_overrun_log: Option<Instant>,
+}
+
+impl ClientQueue {
+    fn new(peer_addr: SocketAddr, max_bytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(ClientQueueInner {
+                queue: VecDeque::new(),
+                queued_bytes: 0,
+                dropped_bytes: 0,
+                closed: false,
+                last_overrun_log: None,
+            }),
+            condvar: Condvar::new(),
+            max_bytes,
+            peer_addr,
+        }
+    }
+
+    /// Queue a buffer for this client, dropping the oldest data on overrun
+    ///
+    /// Returns `false` once the client has closed so the dispatcher can drop
+    /// this queue from its fan-out list.
+    pub(crate) fn push(&self, buf: Avb) -> bool {
+        let Ok(mut inner) = self.inner.lock() else {
+            return false;
+        };
+        if inner.closed {
+            return false;
+        }
+        let incoming = buf.len();
+        let mut dropped_now = 0u64;
+        // Drop the oldest chunks until the newest buffer fits under the cap
+        while inner.queued_bytes + incoming > self.max_bytes {
+            match inner.queue.pop_front() {
+                Some(old) => {
+                    inner.queued_bytes -= old.len();
+                    inner.dropped_bytes += old.len() as u64;
+                    dropped_now += old.len() as u64;
+                }
+                // A single chunk larger than the whole buffer still gets sent
+                None => break,
+            }
+        }
+        inner.queue.push_back(buf);
+        inner.queued_bytes += incoming;
+        if dropped_now > 0 {
+            let now = Instant::now();
+            let should_log = match inner.last_overrun_log {
+                Some(t) => now.duration_since(t) >= Duration::from_secs(1),
+                None => true,
+            };
+            if should_log {
+                eprintln!(
+                    "Overrun on connection {}: dropped {dropped_now} bytes ({} total)",
+                    self.peer_addr, inner.dropped_bytes
+                );
+                inner.last_overrun_log = Some(now);
+            }
+        }
+        self.condvar.notify_one();
+        true
+    }
+
+    /// Mark the client closed and wake the writer so it can exit
+    fn close(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.closed = true;
+        }
+        self.condvar.notify_one();
+    }
+}
+
+/// Create TCP listener on specified bind address+port
+///
+/// Create a bounded queue for each connected TCP client and add it to the list
+/// of serial data receivers
+pub(crate) fn handle_tcp_listener(
+    bind_addr: &str,
+    tcp_write_senders: Arc<Mutex<Vec<Arc<ClientQueue>>>>,
+    serial_write_tx: Option<Sender<Avb>>,
+    client_buffer_bytes: usize,
+) {
+    let tcp_listener = TcpListener::bind(bind_addr);
+    let Ok(tcp_listener) = tcp_listener else {
+        return;
+    };
+    let Ok(local_addr) = tcp_listener.local_addr() else {
+        return;
+    };
+    eprintln!("Listening on: {local_addr}");
+    let mut tcp_stream_threads = Vec::new();
+    for stream in tcp_listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(peer_addr) = stream.peer_addr() else {
+            continue;
+        };
+        let queue = Arc::new(ClientQueue::new(peer_addr, client_buffer_bytes));
+        {
+            let Ok(mut tcp_write_senders) = tcp_write_senders.lock() else {
+                continue;
+            };
+            tcp_write_senders.push(Arc::clone(&queue));
+        }
+        let serial_write_tx = serial_write_tx.clone();
+        let thread = thread::spawn(move || {
+            handle_tcp_stream(stream, queue, serial_write_tx);
+        });
+        tcp_stream_threads.push(thread);
+    }
+    for thread in tcp_stream_threads {
+        match thread.join() {
+            Ok(_) => continue,
+            Err(e) => {
+                eprintln!("Unable to join TCP stream thread: {e:?}");
+                continue;
+            }
+        }
+    }
+}
+
+/// Write queued serial data to the TCP stream, waking on the queue condvar
+///
+/// When `serial_write_tx` is set (bidirectional mode) a second thread reads
+/// from the same socket and forwards non-empty buffers into the serial-write
+/// channel, giving a transparent two-way bridge.
+pub(crate) fn handle_tcp_stream(
+    mut stream: TcpStream,
+    queue: Arc<ClientQueue>,
+    serial_write_tx: Option<Sender<Avb>>,
+) {
+    let peer_addr = queue.peer_addr;
+    eprintln!("New connection from: {peer_addr}");
+    // Forward TCP client input back to the serial port in bidirectional mode
+    if let Some(serial_write_tx) = serial_write_tx {
+        if let Ok(mut read_stream) = stream.try_clone() {
+            thread::spawn(move || {
+                let mut buf = [0; 1024];
+                loop {
+                    match read_stream.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => {
+                            let v = Arc::new(buf[..n].to_vec());
+                            match serial_write_tx.send(v) {
+                                Ok(_) => continue,
+                                Err(e) => {
+                                    eprintln!("Error forwarding data to serial writer: {e}");
+                                    break;
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Reading from connection {peer_addr} failed: {e}");
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    }
+    loop {
+        // Wait for buffered data (or closure) and drain everything pending
+        let drained = {
+            let Ok(mut inner) = queue.inner.lock() else {
+                return;
+            };
+            while inner.queue.is_empty() && !inner.closed {
+                inner = match queue.condvar.wait(inner) {
+                    Ok(inner) => inner,
+                    Err(_) => return,
+                };
+            }
+            if inner.closed && inner.queue.is_empty() {
+                return;
+            }
+            inner.queued_bytes = 0;
+            inner.queue.drain(..).collect::<Vec<Avb>>()
+        };
+        for buf in drained {
+            if let Err(e) = stream.write_all(buf.as_slice()) {
+                eprintln!("Closed connection from: {peer_addr}: {e}");
+                queue.close();
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(max_bytes: usize) -> ClientQueue {
+        let peer_addr = "127.0.0.1:0".parse().unwrap();
+        ClientQueue::new(peer_addr, max_bytes)
+    }
+
+    fn chunk(len: usize) -> Avb {
+        Arc::new(vec![0u8; len])
+    }
+
+    #[test]
+    fn under_cap_keeps_everything() {
+        let queue = queue(10);
+        assert!(queue.push(chunk(4)));
+        assert!(queue.push(chunk(4)));
+        let inner = queue.inner.lock().unwrap();
+        assert_eq!(inner.queue.len(), 2);
+        assert_eq!(inner.queued_bytes, 8);
+        assert_eq!(inner.dropped_bytes, 0);
+    }
+
+    #[test]
+    fn overrun_drops_oldest_chunks() {
+        let queue = queue(10);
+        assert!(queue.push(chunk(6)));
+        // 6 + 6 > 10, so the first chunk is dropped to make room
+        assert!(queue.push(chunk(6)));
+        let inner = queue.inner.lock().unwrap();
+        assert_eq!(inner.queue.len(), 1);
+        assert_eq!(inner.queued_bytes, 6);
+        assert_eq!(inner.dropped_bytes, 6);
+    }
+
+    #[test]
+    fn single_chunk_larger_than_cap_is_still_queued() {
+        let queue = queue(4);
+        assert!(queue.push(chunk(10)));
+        let inner = queue.inner.lock().unwrap();
+        assert_eq!(inner.queue.len(), 1);
+        assert_eq!(inner.queued_bytes, 10);
+        assert_eq!(inner.dropped_bytes, 0);
+    }
+
+    #[test]
+    fn closed_queue_rejects_pushes() {
+        let queue = queue(10);
+        queue.close();
+        assert!(!queue.push(chunk(1)));
+    }
+}